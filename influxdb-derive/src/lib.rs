@@ -22,6 +22,55 @@ pub fn derive_measurement(input: TokenStream) -> TokenStream {
     TokenStream::from(gen.to_token_stream())
 }
 
+/// The read-side counterpart to `#[derive(Measurement)]`: builds a struct
+/// back from one row of an `AsyncDb::query` result, using the same
+/// `#[influx(tag)]`/`#[influx(field)]`/`#[influx(timestamp)]` annotations.
+/// Every field must be annotated; there's no equivalent of an un-annotated
+/// passthrough field, since there'd be no column to read it from.
+///
+/// The `#[influx(timestamp)]` field reads from the `time` column by
+/// default, matching what InfluxQL results call it; add an explicit
+/// `rename = "_time"` if you're deserializing a `query_flux` result instead.
+#[proc_macro_derive(FromDataPoint, attributes(influx))]
+pub fn derive_from_data_point(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    let gen = impl_from_data_point(&ast);
+
+    TokenStream::from(gen.to_token_stream())
+}
+
+fn impl_from_data_point(input: &syn::DeriveInput) -> impl quote::ToTokens {
+    let struct_fields = parse_influx_struct_fields(input);
+
+    let name = &input.ident;
+
+    let field_inits = struct_fields.iter().map(|field| {
+        let field_name = field.field_name();
+        let column_name = field.from_data_point_column();
+        quote! {
+            #field_name: {
+                let __influx_idx = columns.iter().position(|c| c == #column_name)
+                    .ok_or_else(|| influxdb::Error::InvalidColumn(#column_name.to_string()))?;
+                let __influx_value = values.get(__influx_idx)
+                    .ok_or_else(|| influxdb::Error::InvalidColumn(#column_name.to_string()))?;
+                influxdb::query::FromFieldValue::from_json(__influx_value)
+                    .ok_or_else(|| influxdb::Error::InvalidColumn(#column_name.to_string()))?
+            }
+        }
+    });
+
+    quote! {
+        impl influxdb::query::FromDataPoint for #name {
+            fn from_row(columns: &[String], values: &[serde_json::Value]) -> ::std::result::Result<Self, influxdb::Error> {
+                Ok(#name {
+                    #(#field_inits),*
+                })
+            }
+        }
+    }
+}
+
 fn impl_measurement(input: &syn::DeriveInput) -> impl quote::ToTokens {
 
     let struct_fields = parse_influx_struct_fields(input);
@@ -59,15 +108,35 @@ fn impl_measurement(input: &syn::DeriveInput) -> impl quote::ToTokens {
         })
         .intersperse(quote!{ v.push_str(","); });
 
+    // Float fields can be skipped (NaN/Inf) at runtime, so field separators
+    // can't be interspersed statically; track whether anything's been
+    // written yet and emit the comma ourselves.
     let field_stmts = fields.iter()
         .map(|field| {
             let field_name = field.field_name();
             let name = field.name();
-            quote!{
-                influxdb::measurement::Field::new(#name, &self.#field_name).append(v);
+            if field.wants_float() {
+                quote!{
+                    if let Some(__influx_field) = influxdb::measurement::Field::checked_float_from(#name, &self.#field_name, policy)? {
+                        if __influx_field_written { v.push_str(","); }
+                        __influx_field.append(v);
+                        __influx_field_written = true;
+                    }
+                }
+            } else if field.wants_integer() {
+                quote!{
+                    if __influx_field_written { v.push_str(","); }
+                    influxdb::measurement::Field::from_integer(#name, &self.#field_name).append(v);
+                    __influx_field_written = true;
+                }
+            } else {
+                quote!{
+                    if __influx_field_written { v.push_str(","); }
+                    influxdb::measurement::Field::new(#name, &self.#field_name).append(v);
+                    __influx_field_written = true;
+                }
             }
-        })
-        .intersperse(quote!{ v.push_str(","); });
+        });
 
     let timestamp_stmts = timestamps.iter()
         .map(|field| {
@@ -79,20 +148,23 @@ fn impl_measurement(input: &syn::DeriveInput) -> impl quote::ToTokens {
 
     quote!{
         impl influxdb::Measurement for #name {
-            fn to_data(&self, v: &mut String) {
+            fn to_data(&self, v: &mut String, policy: influxdb::measurement::NonFinitePolicy) -> ::std::result::Result<(), influxdb::Error> {
                 use std::string::ToString;
 
-                v.push_str(#measurement_name);
+                v.push_str(&influxdb::measurement::escape_measurement(#measurement_name));
                 #name_and_tag_separator;
                 #(#tag_stmts)*
 
                 v.push_str(" ");
 
+                let mut __influx_field_written = false;
                 #(#field_stmts)*
 
                 v.push_str(" ");
 
                 #(#timestamp_stmts)*
+
+                Ok(())
             }
         }
     }
@@ -106,7 +178,7 @@ fn parse_influx_struct_fields(input: &syn::DeriveInput) -> Vec<InfluxStructField
                     fields.named.iter().filter_map(|field| {
                         parse_influx_db_attrs(&field.attrs).map(|attr| {
                             let field_name = field.ident.clone().expect("All fields must be named");
-                            InfluxStructField::new(field_name, attr)
+                            InfluxStructField::new(field_name, field.ty.clone(), attr)
                         })
                     }).collect()
                 }
@@ -120,13 +192,15 @@ fn parse_influx_struct_fields(input: &syn::DeriveInput) -> Vec<InfluxStructField
 #[derive(Debug)]
 struct InfluxStructField {
     field_name: Ident,
+    ty: syn::Type,
     attr: InfluxAttr,
 }
 
 impl InfluxStructField {
-    fn new(field_name: Ident, attr: InfluxAttr) -> Self {
+    fn new(field_name: Ident, ty: syn::Type, attr: InfluxAttr) -> Self {
         InfluxStructField {
             field_name: field_name,
+            ty: ty,
             attr: attr,
         }
     }
@@ -139,9 +213,62 @@ impl InfluxStructField {
         self.attr.name.clone().unwrap_or_else(|| self.field_name.to_string())
     }
 
+    /// The query result column this field reads from. Same as `name()`,
+    /// except a `#[influx(timestamp)]` field with no explicit `rename`
+    /// defaults to InfluxDB's own `time` column instead of the Rust field's
+    /// name, since that's what every InfluxQL result actually calls it.
+    /// (A Flux result calls it `_time` instead; `rename = "_time"` if
+    /// you're deserializing `query_flux` output.)
+    fn from_data_point_column(&self) -> String {
+        if self.is_timestamp() {
+            self.attr.name.clone().unwrap_or_else(|| "time".to_string())
+        } else {
+            self.name()
+        }
+    }
+
     fn is_tag(&self) -> bool { self.attr.is_tag }
     fn is_field(&self) -> bool { self.attr.is_field }
     fn is_timestamp(&self) -> bool { self.attr.is_timestamp }
+
+    /// Whether this field's type is `f32`/`f64`.
+    fn is_float_type(&self) -> bool {
+        if let syn::Type::Path(ref type_path) = self.ty {
+            if let Some(segment) = type_path.path.segments.last() {
+                return segment.ident == "f32" || segment.ident == "f64";
+            }
+        }
+        false
+    }
+
+    /// Whether this field should be routed through `AsF64`/
+    /// `Field::checked_float_from`, which need the non-finite checked path
+    /// rather than the plain `Field::new`: either its type is `f32`/`f64`,
+    /// or `#[influx(field, float)]` forced it for a newtype that implements
+    /// `AsF64`.
+    fn wants_float(&self) -> bool {
+        self.is_float_type() || self.attr.is_float
+    }
+
+    /// Whether this field's type is one of the built-in Rust integer types.
+    fn is_integer_type(&self) -> bool {
+        if let syn::Type::Path(ref type_path) = self.ty {
+            if let Some(segment) = type_path.path.segments.last() {
+                return matches!(
+                    segment.ident.to_string().as_str(),
+                    "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" | "usize" | "isize"
+                );
+            }
+        }
+        false
+    }
+
+    /// Whether this field should be routed through `AsI64`/`Field::from_integer`:
+    /// either its type is a built-in integer, or `#[influx(field, integer)]`
+    /// forced it for a newtype that implements `AsI64`.
+    fn wants_integer(&self) -> bool {
+        self.is_integer_type() || self.attr.is_integer
+    }
 }
 
 #[derive(Debug, Default)]
@@ -150,6 +277,8 @@ struct InfluxAttr {
     is_tag: bool,
     is_field: bool,
     is_timestamp: bool,
+    is_integer: bool,
+    is_float: bool,
 }
 
 impl InfluxAttr {
@@ -157,6 +286,8 @@ impl InfluxAttr {
         InfluxAttr {
             name: other.name.or(self.name),
             is_tag: other.is_tag || self.is_tag,
+            is_integer: other.is_integer || self.is_integer,
+            is_float: other.is_float || self.is_float,
             is_field: other.is_field || self.is_field,
             is_timestamp: other.is_timestamp || self.is_timestamp,
         }
@@ -200,6 +331,14 @@ fn parse_influx_db_attr<'a>(items: impl IntoIterator<Item = &'a NestedMeta>) ->
                 if ident == "field" { influx_attr.is_field = true }
                 // #[influx(timestamp)]
                 if ident == "timestamp" { influx_attr.is_timestamp = true }
+                // #[influx(field, integer)]: force the `AsI64`/`i`-suffixed
+                // representation for a field whose type isn't already a
+                // built-in Rust integer.
+                if ident == "integer" { influx_attr.is_integer = true }
+                // #[influx(field, float)]: force the `AsF64`/non-finite
+                // checked representation for a field whose type isn't
+                // already `f32`/`f64`.
+                if ident == "float" { influx_attr.is_float = true }
             }
             NestedMeta::Meta(Meta::NameValue(ref metanamevalue)) => {
                 // #[influx(rename = "new_name")]
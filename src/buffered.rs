@@ -0,0 +1,230 @@
+//! A batching, non-blocking front-end for [`AsyncDb`](crate::AsyncDb).
+//!
+//! `add_data` fires one HTTP POST per call, which doesn't scale to
+//! workloads emitting thousands of points per second. [`BufferedDb`] instead
+//! owns a bounded channel and a background writer thread: `add` serializes
+//! the point and hands it to the channel without waiting on the network,
+//! and the writer accumulates lines into a buffer that it flushes to the
+//! `AsyncDb`'s write endpoint as a single request once it reaches `max_buffer` points or
+//! `max_age` has elapsed, whichever comes first.
+
+use std::sync::mpsc::{sync_channel, RecvTimeoutError, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+
+use crate::measurement::NonFinitePolicy;
+use crate::{AsyncDb, Error, Measurement};
+
+/// Default number of points buffered before a flush is forced.
+pub const DEFAULT_MAX_BUFFER: usize = 4096;
+/// Default age the oldest buffered point is allowed to reach before the
+/// buffer is flushed anyway.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(1);
+/// How long `BufferedDb::drop` waits for the writer to flush its remaining
+/// buffer before giving up and letting the process continue shutting down.
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+
+enum Command {
+    Point(String),
+    Flush(oneshot::Sender<Result<(), Error>>),
+}
+
+/// A batching, non-blocking front-end for `AsyncDb`.
+///
+/// Construct one with [`AsyncDb::buffered`] or [`AsyncDb::buffered_default`].
+pub struct BufferedDb {
+    sender: Option<Sender<Command>>,
+    done: Option<std::sync::mpsc::Receiver<()>>,
+    non_finite_policy: NonFinitePolicy,
+}
+
+impl BufferedDb {
+    pub(crate) fn new(db: AsyncDb, max_buffer: usize, max_age: Duration) -> Self {
+        let non_finite_policy = db.non_finite_policy;
+        let (sender, receiver) = bounded(max_buffer.max(1) * 2);
+        let (done_tx, done_rx) = sync_channel(1);
+        let handle = Handle::current();
+
+        thread::spawn(move || {
+            Self::run(db, receiver, max_buffer, max_age, &handle, done_tx);
+        });
+
+        BufferedDb {
+            sender: Some(sender),
+            done: Some(done_rx),
+            non_finite_policy,
+        }
+    }
+
+    /// Enqueues `measure` for a future batched write without making an HTTP
+    /// request itself. Blocks only if the channel is full, until the writer
+    /// (which may be in the middle of a flush) takes points off it.
+    /// If the writer has already shut down, the point is silently dropped.
+    pub fn add<T: Measurement>(&self, measure: T) -> Result<(), Error> {
+        let mut line = String::new();
+        measure.to_data(&mut line, self.non_finite_policy)?;
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(Command::Point(line));
+        }
+        Ok(())
+    }
+
+    /// Forces an immediate flush of whatever is currently buffered and waits
+    /// for it to complete.
+    pub async fn flush(&self) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        match &self.sender {
+            Some(sender) if sender.send(Command::Flush(tx)).is_ok() => {
+                rx.await.unwrap_or(Ok(()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn run(
+        db: AsyncDb,
+        receiver: Receiver<Command>,
+        max_buffer: usize,
+        max_age: Duration,
+        handle: &Handle,
+        done: SyncSender<()>,
+    ) {
+        let mut buffer = String::new();
+        let mut count = 0usize;
+        let mut oldest: Option<Instant> = None;
+
+        loop {
+            let timeout = match oldest {
+                Some(start) => max_age.saturating_sub(start.elapsed()),
+                None => max_age,
+            };
+
+            match receiver.recv_timeout(timeout) {
+                Ok(Command::Point(line)) => {
+                    append_line(&mut buffer, &line);
+                    count += 1;
+                    if oldest.is_none() {
+                        oldest = Some(Instant::now());
+                    }
+                    if count >= max_buffer {
+                        if let Err(e) = handle.block_on(Self::send_batch(&db, &mut buffer)) {
+                            log::error!("BufferedDb: flush on max_buffer failed, batch dropped: {}", e);
+                        }
+                        count = 0;
+                        oldest = None;
+                    }
+                }
+                Ok(Command::Flush(reply)) => {
+                    let result = handle.block_on(Self::send_batch(&db, &mut buffer));
+                    count = 0;
+                    oldest = None;
+                    let _ = reply.send(result);
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if !buffer.is_empty() {
+                        if let Err(e) = handle.block_on(Self::send_batch(&db, &mut buffer)) {
+                            log::error!("BufferedDb: flush on max_age failed, batch dropped: {}", e);
+                        }
+                        count = 0;
+                        oldest = None;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    let _ = handle.block_on(Self::send_batch(&db, &mut buffer));
+                    break;
+                }
+            }
+        }
+
+        let _ = done.send(());
+    }
+
+    async fn send_batch(db: &AsyncDb, buffer: &mut String) -> Result<(), Error> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        let body = std::mem::take(buffer);
+        db.write_raw(body).await.map(|_| ())
+    }
+}
+
+/// Appends one serialized Line Protocol line to the writer's buffer,
+/// newline-separating it from whatever's already there. Pulled out of
+/// `run`'s `Command::Point` arm so the "don't lead with a blank line" edge
+/// case is unit-testable without spinning up the writer thread.
+fn append_line(buffer: &mut String, line: &str) {
+    if !buffer.is_empty() {
+        buffer.push('\n');
+    }
+    buffer.push_str(line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsyncDb, Measurement};
+
+    struct Point;
+
+    impl Measurement for Point {
+        fn to_data(&self, v: &mut String, _policy: NonFinitePolicy) -> Result<(), Error> {
+            v.push_str("test_point count=1i");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn append_line_joins_with_newline() {
+        let mut buffer = String::new();
+        append_line(&mut buffer, "a");
+        append_line(&mut buffer, "b");
+        assert_eq!(buffer, "a\nb");
+    }
+
+    #[test]
+    fn append_line_does_not_lead_with_a_blank_line() {
+        let mut buffer = String::new();
+        append_line(&mut buffer, "only");
+        assert_eq!(buffer, "only");
+    }
+
+    // These exercise the add/flush/shutdown wiring end-to-end against an
+    // endpoint that can't be reached: the writes fail, but the point is to
+    // prove the channel, writer thread, and `Drop` shutdown handshake never
+    // hang, independent of whether the HTTP call itself succeeds.
+    #[tokio::test]
+    async fn add_and_flush_complete_even_when_writes_fail() {
+        let db = AsyncDb::new("http://127.0.0.1:1/", "test").unwrap();
+        let buffered = db.buffered(8, Duration::from_secs(60));
+
+        buffered.add(Point).unwrap();
+        // `flush` must resolve (with an error, since nothing's listening on
+        // that port) rather than hang waiting on the writer.
+        assert!(buffered.flush().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn drop_waits_for_shutdown_flush_without_hanging() {
+        let db = AsyncDb::new("http://127.0.0.1:1/", "test").unwrap();
+        let buffered = db.buffered_default();
+        drop(buffered);
+    }
+}
+
+impl Drop for BufferedDb {
+    fn drop(&mut self) {
+        // Dropping the sender closes the channel, which wakes the writer so
+        // it can flush whatever's left and exit. We wait up to
+        // `SHUTDOWN_DEADLINE` for that to happen so pending points aren't
+        // lost on a normal shutdown, but don't block forever if the writer
+        // is stuck on a hung request.
+        self.sender.take();
+        if let Some(done) = self.done.take() {
+            let _ = done.recv_timeout(SHUTDOWN_DEADLINE);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! A small builder for Flux queries, and the annotated-CSV parsing that
+//! backs `AsyncDb::query_flux`.
+
+use std::time::{Duration, SystemTime};
+
+enum FluxTime {
+    Absolute(SystemTime),
+    Ago(Duration),
+}
+
+/// Builds a Flux query for a bucket over a time range, with optional tag
+/// filters, so callers don't have to hand-concatenate query strings.
+///
+/// ```ignore
+/// let flux = FluxQuery::new("my-bucket")
+///     .range_since(Duration::from_secs(3600))
+///     .filter_tag("region", "us-east")
+///     .to_flux();
+/// ```
+pub struct FluxQuery {
+    bucket: String,
+    start: Option<FluxTime>,
+    stop: Option<FluxTime>,
+    filters: Vec<(String, String)>,
+}
+
+impl FluxQuery {
+    pub fn new(bucket: &str) -> Self {
+        FluxQuery {
+            bucket: bucket.to_string(),
+            start: None,
+            stop: None,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Bounds the query to `[start, stop]`.
+    pub fn range(mut self, start: SystemTime, stop: SystemTime) -> Self {
+        self.start = Some(FluxTime::Absolute(start));
+        self.stop = Some(FluxTime::Absolute(stop));
+        self
+    }
+
+    /// Bounds the query to `[now - ago, now]`.
+    pub fn range_since(mut self, ago: Duration) -> Self {
+        self.start = Some(FluxTime::Ago(ago));
+        self.stop = None;
+        self
+    }
+
+    /// Adds an `r["tag"] == "value"` filter. Can be called more than once.
+    pub fn filter_tag(mut self, tag: &str, value: &str) -> Self {
+        self.filters.push((tag.to_string(), value.to_string()));
+        self
+    }
+
+    /// Renders this builder into a Flux query string.
+    pub fn to_flux(&self) -> String {
+        let now = SystemTime::now();
+
+        let start_expr = match &self.start {
+            Some(FluxTime::Absolute(t)) => offset_from(now, *t),
+            Some(FluxTime::Ago(d)) => format!("-{}s", d.as_secs()),
+            None => "-1h".to_string(),
+        };
+        let stop_expr = match &self.stop {
+            Some(FluxTime::Absolute(t)) => offset_from(now, *t),
+            _ => "now()".to_string(),
+        };
+
+        let mut flux = format!(
+            "from(bucket: \"{}\") |> range(start: {}, stop: {})",
+            escape_flux_string(&self.bucket),
+            start_expr,
+            stop_expr,
+        );
+
+        for (tag, value) in &self.filters {
+            flux.push_str(&format!(
+                " |> filter(fn: (r) => r[\"{}\"] == \"{}\")",
+                escape_flux_string(tag),
+                escape_flux_string(value),
+            ));
+        }
+
+        flux
+    }
+}
+
+/// Escapes a value for interpolation into a Flux double-quoted string
+/// literal: backslashes first, then quotes, same order as
+/// `measurement::escape_string_field` and for the same reason — escaping
+/// the quote first would let a trailing backslash in the input swallow it.
+fn escape_flux_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Renders `t` as a Flux duration offset from `now`: `-30s` if it's in the
+/// past, `now() + 30s` if it's in the future.
+fn offset_from(now: SystemTime, t: SystemTime) -> String {
+    if t <= now {
+        format!("-{}s", now.duration_since(t).unwrap_or_default().as_secs())
+    } else {
+        format!("now() + {}s", t.duration_since(now).unwrap_or_default().as_secs())
+    }
+}
+
+/// Parses a Flux annotated-CSV response body into `(columns, rows)` per
+/// table. Annotation lines (`#datatype`, `#group`, `#default`) are skipped;
+/// a blank line ends a table and the next non-blank line starts a new
+/// header, since Flux restarts the header for each table in a multi-table
+/// result.
+pub(crate) fn parse_annotated_csv(text: &str) -> Vec<(Vec<String>, Vec<Vec<serde_json::Value>>)> {
+    let mut tables = Vec::new();
+    let mut columns: Option<Vec<String>> = None;
+    let mut rows: Vec<Vec<serde_json::Value>> = Vec::new();
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if let Some(cols) = columns.take() {
+                tables.push((cols, std::mem::take(&mut rows)));
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let cells = split_csv_line(line);
+        if columns.is_none() {
+            columns = Some(cells);
+        } else {
+            rows.push(cells.iter().map(|c| cell_to_json(c)).collect());
+        }
+    }
+
+    if let Some(cols) = columns {
+        tables.push((cols, rows));
+    }
+
+    tables
+}
+
+/// Splits one line of annotated-CSV into cells, RFC4180-style: a cell
+/// wrapped in double quotes may contain commas (and newlines, though
+/// `text.lines()` already splits those) that don't end it, and `""` inside
+/// a quoted cell is an escaped literal quote. Flux's CSV encoder quotes any
+/// cell containing a comma, so a naive `split(',')` would shift every later
+/// column whenever a string value has one embedded.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    cell.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                cell.push(c);
+            }
+        } else {
+            match c {
+                '"' => in_quotes = true,
+                ',' => {
+                    cells.push(std::mem::take(&mut cell));
+                }
+                _ => cell.push(c),
+            }
+        }
+    }
+    cells.push(cell);
+
+    cells
+}
+
+fn cell_to_json(cell: &str) -> serde_json::Value {
+    if cell.is_empty() {
+        serde_json::Value::Null
+    } else if let Ok(i) = cell.parse::<i64>() {
+        serde_json::Value::from(i)
+    } else if let Ok(f) = cell.parse::<f64>() {
+        serde_json::Value::from(f)
+    } else if let Ok(b) = cell.parse::<bool>() {
+        serde_json::Value::from(b)
+    } else {
+        serde_json::Value::from(cell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn escape_flux_string_escapes_quotes_and_backslashes() {
+        assert_eq!(escape_flux_string("say \"hi\""), "say \\\"hi\\\"");
+        assert_eq!(escape_flux_string("a\\"), "a\\\\");
+    }
+
+    #[test]
+    fn escape_flux_string_escapes_backslash_before_quote() {
+        // A trailing backslash right before the inserted closing-quote
+        // escape must not combine into an unescaped closing quote.
+        assert_eq!(escape_flux_string("x\\"), "x\\\\");
+    }
+
+    #[test]
+    fn parse_annotated_csv_splits_quoted_comma_cell() {
+        let text = "\
+#datatype,string,long
+,result,table
+,_result,0
+,mine,\"a,b\"
+";
+        let tables = parse_annotated_csv(text);
+        assert_eq!(tables.len(), 1);
+        let (columns, rows) = &tables[0];
+        assert_eq!(columns, &vec!["".to_string(), "result".to_string(), "table".to_string()]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0], vec![json!(null), json!("mine"), json!("a,b")]);
+    }
+
+    #[test]
+    fn parse_annotated_csv_handles_multiple_tables() {
+        let text = "\
+,a,b
+,1,2
+
+,c,d
+,3,4
+";
+        let tables = parse_annotated_csv(text);
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].0, vec!["".to_string(), "a".to_string(), "b".to_string()]);
+        assert_eq!(tables[1].0, vec!["".to_string(), "c".to_string(), "d".to_string()]);
+    }
+}
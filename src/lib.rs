@@ -74,8 +74,14 @@ use log::error;
 #[macro_use]
 extern crate quick_error;
 
+pub mod buffered;
+pub mod flux;
 pub mod measurement;
-pub use measurement::Measurement;
+pub mod query;
+pub use buffered::BufferedDb;
+pub use flux::FluxQuery;
+pub use measurement::{AsF64, AsI64, Measurement};
+pub use query::{FromDataPoint, QueryResponse, QueryResult, Series};
 
 quick_error! {
     #[derive(Debug)]
@@ -108,14 +114,35 @@ quick_error! {
             from()
             cause(error)
         }
+        NonFiniteValue(field: String) {
+            description("A float field held a non-finite value (NaN or +/-Inf)")
+            display("Field '{}' is NaN or infinite, which InfluxDB cannot store", field)
+        }
+        InvalidColumn(column: String) {
+            description("A column expected by FromDataPoint was missing or held an unexpected type")
+            display("Column '{}' was missing from the query result, or didn't hold the expected type", column)
+        }
     }
 }
 
+/// Which write/query API an `AsyncDb` targets, and the auth that goes with
+/// it. `AsyncDb::new` selects `V1`; `AsyncDb::new_v2` selects `V2`.
+#[derive(Debug, Clone)]
+pub enum ApiVersion {
+    /// The 1.x `/write?db=` and `/query?db=` API. No authentication.
+    V1,
+    /// The 2.x `/api/v2/write?org=&bucket=` API, authenticated with a
+    /// `Authorization: Token <token>` header on every request.
+    V2 { token: String },
+}
+
 pub struct AsyncDb {
     name: String,
     query_endpoint: url::Url,
     write_endpoint: url::Url,
     client: reqwest::Client,
+    non_finite_policy: measurement::NonFinitePolicy,
+    api_version: ApiVersion,
 }
 
 impl AsyncDb {
@@ -130,13 +157,57 @@ impl AsyncDb {
             query_endpoint: query_endpoint,
             write_endpoint: write_endpoint,
             client: Client::new(),
+            non_finite_policy: measurement::NonFinitePolicy::default(),
+            api_version: ApiVersion::V1,
         })
     }
 
+    /// Targets an InfluxDB 2.x (or Cloud) instance's `/api/v2/write` and
+    /// `/api/v2/query` endpoints, authenticating with `token`.
+    pub fn new_v2(base_url: &str, org: &str, bucket: &str, token: &str) -> Result<Self, Error> {
+        let base_url = url::Url::parse(base_url)?;
+        let mut query_endpoint = base_url.join("/api/v2/query")?;
+        query_endpoint.query_pairs_mut().append_pair("org", org);
+        let mut write_endpoint = base_url.join("/api/v2/write")?;
+        write_endpoint
+            .query_pairs_mut()
+            .append_pair("org", org)
+            .append_pair("bucket", bucket)
+            .append_pair("precision", "ns");
+
+        Ok(AsyncDb {
+            name: bucket.into(),
+            query_endpoint: query_endpoint,
+            write_endpoint: write_endpoint,
+            client: Client::new(),
+            non_finite_policy: measurement::NonFinitePolicy::default(),
+            api_version: ApiVersion::V2 { token: token.into() },
+        })
+    }
+
+    /// Controls what happens when a float field is `NaN` or infinite: either
+    /// `Skip` the field (the rest of the line still writes) or `Reject` the
+    /// whole point with `Error::NonFiniteValue`. Defaults to `Skip`.
+    pub fn with_non_finite_policy(mut self, policy: measurement::NonFinitePolicy) -> Self {
+        self.non_finite_policy = policy;
+        self
+    }
+
     pub async fn add_data<T: Measurement>(&self, measure: T) -> Result<Response, Error> {
         let mut bytes_to_send = String::new();
-        measure.to_data(&mut bytes_to_send);
-        let response = self.client.post(self.write_endpoint.clone()).body(bytes_to_send).send().await.map_err(Error::Reqwest);
+        measure.to_data(&mut bytes_to_send, self.non_finite_policy)?;
+        self.write_raw(bytes_to_send).await
+    }
+
+    /// Posts an already-serialized batch of Line Protocol (one or more
+    /// newline-separated lines) to the write endpoint. `add_data` and
+    /// `BufferedDb`'s flusher both funnel through here.
+    pub(crate) async fn write_raw(&self, lines: String) -> Result<Response, Error> {
+        let mut request = self.client.post(self.write_endpoint.clone()).body(lines);
+        if let ApiVersion::V2 { token } = &self.api_version {
+            request = request.header("Authorization", format!("Token {}", token));
+        }
+        let response = request.send().await.map_err(Error::Reqwest);
         match response {
             Ok(r) => {
                 if r.status().is_client_error() {
@@ -148,43 +219,97 @@ impl AsyncDb {
             Err(e) => Err(e)
         }
     }
-//
-//    pub fn query(&self, query: &str) -> Query {
-//        let mut query_endpoint = self.query_endpoint.clone();
-//        query_endpoint.query_pairs_mut()
-//            .append_pair("db", &self.name)
-//            .append_pair("q", query);
-//
-//        let response =
-//            self.client.get(query_endpoint.as_str().parse().expect("Invalid query URL"))
-//            .map_err(Error::Hyper)
-//            .and_then(check_response_code)
-//            .and_then(response_to_json);
-//
-//        Query(Box::new(response))
-//    }
-}
 
-//#[derive(Debug, Deserialize)]
-//pub struct QueryResponse {
-//    pub results: Vec<QueryResult>,
-//}
-//
-//#[derive(Debug, Deserialize)]
-//pub struct QueryResult {
-//    #[serde(default)]
-//    pub series: Vec<Series>,
-//    pub error: Option<String>,
-//    pub statement_id: usize,
-//}
-//
-//#[derive(Debug, Deserialize)]
-//pub struct Series {
-//    pub name: String,
-//    pub columns: Vec<String>, // TODO: `time` is always added?
-//    pub values: Vec<Vec<serde_json::Value>>, // TODO: matches with columns?
-//    // TODO: Don't expose serde types publically
-//}
+    /// Wraps this `AsyncDb` in a [`BufferedDb`] that batches points added via
+    /// `BufferedDb::add` and flushes them together, instead of one HTTP
+    /// request per point.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime context (it captures a
+    /// `tokio::runtime::Handle` to drive the writer's HTTP calls).
+    pub fn buffered(self, max_buffer: usize, max_age: std::time::Duration) -> BufferedDb {
+        BufferedDb::new(self, max_buffer, max_age)
+    }
+
+    /// Like `buffered`, but with the default batching policy (4096 points or
+    /// 1 second, whichever comes first).
+    ///
+    /// # Panics
+    ///
+    /// Same as `buffered`: requires a Tokio runtime context.
+    pub fn buffered_default(self) -> BufferedDb {
+        self.buffered(buffered::DEFAULT_MAX_BUFFER, buffered::DEFAULT_MAX_AGE)
+    }
+
+    /// Runs an InfluxQL query and parses the response into a `QueryResponse`.
+    /// Use `Series::rows` with a `#[derive(FromDataPoint)]` struct to
+    /// convert a result's rows into typed values.
+    ///
+    /// InfluxDB 2.x doesn't speak InfluxQL over `/query`; use `query_flux`
+    /// for an `AsyncDb` built with `new_v2`.
+    pub async fn query(&self, q: &str) -> Result<query::QueryResponse, Error> {
+        if let ApiVersion::V2 { .. } = &self.api_version {
+            return Err(Error::BadRequest(
+                "InfluxQL queries aren't supported against AsyncDb::new_v2; use query_flux instead".to_string(),
+            ));
+        }
+
+        let mut query_endpoint = self.query_endpoint.clone();
+        {
+            let mut pairs = query_endpoint.query_pairs_mut();
+            pairs.append_pair("db", &self.name);
+            pairs.append_pair("q", q);
+            pairs.append_pair("epoch", "ns");
+        }
+
+        let response = self.client.get(query_endpoint).send().await.map_err(Error::Reqwest)?;
+        if response.status().is_client_error() {
+            return Err(Error::BadRequest(response.text().await.unwrap()));
+        }
+
+        let text = response.text().await.map_err(Error::Reqwest)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Runs a Flux query (see [`flux::FluxQuery`] for a builder) against the
+    /// 2.x `/api/v2/query` endpoint and converts every row of every table in
+    /// the response into a `T` via `FromDataPoint`. Requires `new_v2`.
+    pub async fn query_flux<T: query::FromDataPoint>(&self, flux: &str) -> Result<Vec<T>, Error> {
+        let token = match &self.api_version {
+            ApiVersion::V2 { token } => token,
+            ApiVersion::V1 => {
+                return Err(Error::BadRequest(
+                    "Flux queries require AsyncDb::new_v2".to_string(),
+                ))
+            }
+        };
+
+        let response = self
+            .client
+            .post(self.query_endpoint.clone())
+            .header("Authorization", format!("Token {}", token))
+            .header("Content-Type", "application/vnd.flux")
+            .header("Accept", "application/csv")
+            .body(flux.to_string())
+            .send()
+            .await
+            .map_err(Error::Reqwest)?;
+
+        if response.status().is_client_error() {
+            return Err(Error::BadRequest(response.text().await.unwrap()));
+        }
+
+        let text = response.text().await.map_err(Error::Reqwest)?;
+
+        flux::parse_annotated_csv(&text)
+            .into_iter()
+            .flat_map(|(columns, rows)| {
+                rows.into_iter().map(move |row| T::from_row(&columns, &row)).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
 
 #[derive(Debug, Deserialize)]
 pub struct InfluxServerError {
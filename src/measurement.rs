@@ -0,0 +1,298 @@
+//! Line Protocol serialization primitives.
+//!
+//! These types are what `#[derive(Measurement)]` generates calls into; you
+//! normally won't construct them by hand. See the crate-level docs for how
+//! to derive `Measurement` on your own structs.
+
+use std::time::SystemTime;
+
+use crate::Error;
+
+/// What to do with a float field whose value is `NaN` or infinite.
+/// InfluxDB rejects such values outright, so the derive checks them before
+/// they ever reach the wire. Set via `AsyncDb::with_non_finite_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Drop the offending field but still write the rest of the point.
+    ///
+    /// Note that if every `#[influx(field)]` on a measurement is a
+    /// non-finite float, `Skip` still produces a point with no fields at
+    /// all (e.g. `measurement,tag=v  <timestamp>`), which InfluxDB rejects
+    /// just like it would the original NaN. `Skip` only helps when at
+    /// least one field on the point survives.
+    Skip,
+    /// Fail the whole point with `Error::NonFiniteValue`.
+    Reject,
+}
+
+impl Default for NonFinitePolicy {
+    fn default() -> Self {
+        NonFinitePolicy::Skip
+    }
+}
+
+/// Escapes a measurement name per the Line Protocol rules: backslash-escapes
+/// spaces and commas. Unlike `escape_string_field`, this does *not* escape
+/// backslashes: the measurement/tag/field-key grammar has no backslash
+/// escape sequence, so a literal backslash is left as-is and passes through
+/// to InfluxDB unchanged.
+pub fn escape_measurement(s: &str) -> String {
+    s.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+/// Escapes a tag key, tag value, or field key per the Line Protocol rules:
+/// backslash-escapes spaces, commas, and equals signs. As with
+/// `escape_measurement`, backslashes themselves are left untouched.
+pub fn escape_tag_or_field_key(s: &str) -> String {
+    s.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Escapes a string field value per the Line Protocol rules: backslash-
+/// escapes double quotes and backslashes. The caller is still responsible
+/// for wrapping the result in double quotes.
+pub fn escape_string_field(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Implemented for anything that can be written to an `AsyncDb` as a single
+/// InfluxDB Line Protocol line. `#[derive(Measurement)]` implements this for
+/// you; you shouldn't need to implement it by hand.
+pub trait Measurement {
+    /// Appends this measurement's Line Protocol representation to `v`,
+    /// applying `policy` to any non-finite float fields.
+    fn to_data(&self, v: &mut String, policy: NonFinitePolicy) -> Result<(), Error>;
+}
+
+/// An InfluxDB tag: an indexed, string-valued key on a measurement.
+pub struct Tag {
+    name: String,
+    value: String,
+}
+
+impl Tag {
+    pub fn new(name: &str, value: &str) -> Self {
+        Tag {
+            name: escape_tag_or_field_key(name),
+            value: escape_tag_or_field_key(value),
+        }
+    }
+
+    /// Appends `key=value` for this tag to `v`.
+    pub fn append(&self, v: &mut String) {
+        v.push_str(&self.name);
+        v.push('=');
+        v.push_str(&self.value);
+    }
+}
+
+/// Converts a field value into the `i64` InfluxDB stores its integer fields
+/// as. Implemented for the built-in Rust integer types; the derive routes
+/// any `#[influx(field)]` on one of those types (or `#[influx(field,
+/// integer)]` on anything else) through here, rather than relying on type
+/// inference to pick a sensible `Field::new` impl. Implement it for your
+/// own newtypes to use them as integer fields.
+pub trait AsI64 {
+    fn as_i64(&self) -> i64;
+}
+
+macro_rules! impl_as_i64 {
+    ($($t:ty),*) => {
+        $(
+            impl AsI64 for $t {
+                fn as_i64(&self) -> i64 {
+                    *self as i64
+                }
+            }
+        )*
+    };
+}
+
+impl_as_i64!(i8, i16, i32, i64, u8, u16, u32, u64, usize, isize);
+
+/// Converts a field value into the `f64` InfluxDB stores its float fields
+/// as. Implemented for `f32`/`f64`; the derive routes any `#[influx(field)]`
+/// on one of those types through here. Implement it for your own newtypes
+/// to use them as float fields.
+pub trait AsF64 {
+    fn as_f64(&self) -> f64;
+}
+
+impl AsF64 for f32 {
+    fn as_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl AsF64 for f64 {
+    fn as_f64(&self) -> f64 {
+        *self
+    }
+}
+
+/// Implemented for the Rust types that can appear as an InfluxDB field
+/// value. Each impl knows how to render itself in Line Protocol, e.g.
+/// integers get an `i` suffix and strings are quoted.
+pub trait FieldValue {
+    fn to_field_value(&self) -> String;
+}
+
+macro_rules! impl_integer_field_value {
+    ($($t:ty),*) => {
+        $(
+            impl FieldValue for $t {
+                fn to_field_value(&self) -> String {
+                    format!("{}i", self)
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_field_value!(i8, i16, i32, i64, u8, u16, u32, u64, usize, isize);
+
+impl FieldValue for f32 {
+    fn to_field_value(&self) -> String {
+        format!("{}", self)
+    }
+}
+
+impl FieldValue for f64 {
+    fn to_field_value(&self) -> String {
+        format!("{}", self)
+    }
+}
+
+impl FieldValue for bool {
+    fn to_field_value(&self) -> String {
+        if *self { "t".to_string() } else { "f".to_string() }
+    }
+}
+
+impl FieldValue for String {
+    fn to_field_value(&self) -> String {
+        format!("\"{}\"", escape_string_field(self))
+    }
+}
+
+impl FieldValue for str {
+    fn to_field_value(&self) -> String {
+        format!("\"{}\"", escape_string_field(self))
+    }
+}
+
+impl<'a, T: FieldValue + ?Sized> FieldValue for &'a T {
+    fn to_field_value(&self) -> String {
+        (*self).to_field_value()
+    }
+}
+
+/// An InfluxDB field: an unindexed, typed value on a measurement.
+pub struct Field {
+    name: String,
+    value: String,
+}
+
+impl Field {
+    pub fn new<T: FieldValue + ?Sized>(name: &str, value: &T) -> Self {
+        Field {
+            name: escape_tag_or_field_key(name),
+            value: value.to_field_value(),
+        }
+    }
+
+    /// Appends `key=value` for this field to `v`.
+    pub fn append(&self, v: &mut String) {
+        v.push_str(&self.name);
+        v.push('=');
+        v.push_str(&self.value);
+    }
+
+    /// Builds an integer field via `AsI64`, always rendered with the `i`
+    /// suffix InfluxDB expects for integers.
+    pub fn from_integer<T: AsI64 + ?Sized>(name: &str, value: &T) -> Field {
+        Field::new(name, &value.as_i64())
+    }
+
+    /// Builds a float field via `AsF64`, applying `policy` if the converted
+    /// value is `NaN` or infinite.
+    pub fn checked_float_from<T: AsF64 + ?Sized>(name: &str, value: &T, policy: NonFinitePolicy) -> Result<Option<Field>, Error> {
+        Field::checked_float(name, value.as_f64(), policy)
+    }
+
+    /// Builds a float field, applying `policy` if `value` is `NaN` or
+    /// infinite. Returns `Ok(None)` when the field should be silently
+    /// omitted from the line.
+    pub fn checked_float(name: &str, value: f64, policy: NonFinitePolicy) -> Result<Option<Field>, Error> {
+        if value.is_finite() {
+            Ok(Some(Field::new(name, &value)))
+        } else {
+            match policy {
+                NonFinitePolicy::Skip => Ok(None),
+                NonFinitePolicy::Reject => Err(Error::NonFiniteValue(name.to_string())),
+            }
+        }
+    }
+}
+
+/// An InfluxDB timestamp, rendered as Unix nanoseconds.
+pub struct Timestamp(u128);
+
+impl Timestamp {
+    pub fn new(t: SystemTime) -> Self {
+        let nanos = t
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        Timestamp(nanos)
+    }
+
+    pub fn append(&self, v: &mut String) {
+        v.push_str(&self.0.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_measurement_escapes_spaces_and_commas() {
+        assert_eq!(escape_measurement("cpu usage"), "cpu\\ usage");
+        assert_eq!(escape_measurement("cpu,usage"), "cpu\\,usage");
+    }
+
+    #[test]
+    fn escape_measurement_leaves_backslashes_alone() {
+        // No `\` escape sequence exists in the measurement/tag/field-key
+        // grammar, so a literal backslash (e.g. a Windows path) must round
+        // trip unchanged rather than being doubled.
+        assert_eq!(escape_measurement("C:\\data"), "C:\\data");
+    }
+
+    #[test]
+    fn escape_tag_or_field_key_escapes_spaces_commas_and_equals() {
+        assert_eq!(escape_tag_or_field_key("a b"), "a\\ b");
+        assert_eq!(escape_tag_or_field_key("a,b"), "a\\,b");
+        assert_eq!(escape_tag_or_field_key("a=b"), "a\\=b");
+    }
+
+    #[test]
+    fn escape_tag_or_field_key_leaves_backslashes_alone() {
+        assert_eq!(escape_tag_or_field_key("C:\\data"), "C:\\data");
+    }
+
+    #[test]
+    fn escape_string_field_escapes_quotes_and_backslashes() {
+        assert_eq!(escape_string_field("say \"hi\""), "say \\\"hi\\\"");
+        assert_eq!(escape_string_field("C:\\data"), "C:\\\\data");
+    }
+
+    #[test]
+    fn escape_string_field_escapes_backslash_before_quote() {
+        // A trailing backslash immediately before a quote must not combine
+        // with the quote's own escape to produce an unescaped closing quote.
+        assert_eq!(escape_string_field("a\\\""), "a\\\\\\\"");
+    }
+}
@@ -0,0 +1,106 @@
+//! Typed deserialization of InfluxQL query results.
+//!
+//! `AsyncDb::query` returns a [`QueryResponse`] of raw, untyped
+//! `serde_json::Value`s; `#[derive(FromDataPoint)]` generates the
+//! `FromDataPoint` impl needed to convert a [`Series`]' rows back into your
+//! own structs, the read-side counterpart to `#[derive(Measurement)]`.
+
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use crate::Error;
+
+/// The top-level response from `/query`.
+#[derive(Debug, Deserialize)]
+pub struct QueryResponse {
+    pub results: Vec<QueryResult>,
+}
+
+/// One result, corresponding to one statement in the query.
+#[derive(Debug, Deserialize)]
+pub struct QueryResult {
+    #[serde(default)]
+    pub series: Vec<Series>,
+    pub error: Option<String>,
+    pub statement_id: usize,
+}
+
+/// One series of rows sharing a set of columns.
+#[derive(Debug, Deserialize)]
+pub struct Series {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub values: Vec<Vec<serde_json::Value>>,
+}
+
+impl Series {
+    /// Converts every row in this series into a `T` via `FromDataPoint`.
+    pub fn rows<T: FromDataPoint>(&self) -> Result<Vec<T>, Error> {
+        self.values.iter().map(|row| T::from_row(&self.columns, row)).collect()
+    }
+}
+
+/// Implemented for structs that can be built back from a query result row.
+/// `#[derive(FromDataPoint)]` implements this for you from the same
+/// `#[influx(tag)]`/`#[influx(field)]`/`#[influx(timestamp)]` annotations
+/// used by `#[derive(Measurement)]`.
+pub trait FromDataPoint: Sized {
+    /// Builds `Self` from one result row, matching `columns` by name
+    /// (honoring `rename`) against `values` at the same index.
+    fn from_row(columns: &[String], values: &[serde_json::Value]) -> Result<Self, Error>;
+}
+
+/// Implemented for the Rust types a query result column can be converted
+/// into. Mirrors `measurement::FieldValue`, but in the read direction.
+pub trait FromFieldValue: Sized {
+    fn from_json(value: &serde_json::Value) -> Option<Self>;
+}
+
+impl FromFieldValue for String {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_str().map(str::to_string)
+    }
+}
+
+impl FromFieldValue for bool {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl FromFieldValue for f32 {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_f64().map(|f| f as f32)
+    }
+}
+
+impl FromFieldValue for f64 {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_f64()
+    }
+}
+
+macro_rules! impl_from_field_value_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromFieldValue for $t {
+                fn from_json(value: &serde_json::Value) -> Option<Self> {
+                    value.as_i64().and_then(|i| <$t>::try_from(i).ok())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_field_value_int!(i8, i16, i32, i64, u8, u16, u32, u64, usize, isize);
+
+/// `time` columns come back as Unix-epoch nanoseconds when the query is run
+/// with `epoch=ns` precision (the precision `AsyncDb::query` asks for).
+impl FromFieldValue for SystemTime {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value
+            .as_i64()
+            .map(|nanos| SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos as u64))
+    }
+}